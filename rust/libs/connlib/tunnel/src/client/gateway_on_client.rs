@@ -1,6 +1,8 @@
 use std::{
     collections::HashSet,
+    fmt,
     net::{IpAddr, SocketAddr},
+    sync::LazyLock,
 };
 
 use connlib_model::{GatewayId, ResourceId};
@@ -8,6 +10,7 @@ use ip_network::IpNetwork;
 use ip_network_table::IpNetworkTable;
 use ip_packet::IpPacket;
 
+use crate::peer_store::{RedactedIp, canonical};
 use crate::{IpConfig, NotAllowedResource};
 
 /// The state of one gateway on a client.
@@ -15,6 +18,7 @@ pub(crate) struct GatewayOnClient {
     id: GatewayId,
     gateway_tun: IpConfig,
     allowed_ips: IpNetworkTable<HashSet<ResourceId>>,
+    ip_filter: IpFilter,
 }
 
 impl GatewayOnClient {
@@ -46,6 +50,21 @@ impl GatewayOnClient {
         self.allowed_ips.is_empty()
     }
 
+    /// The resource CIDRs this gateway is responsible for, beyond its own TUN addresses.
+    pub(crate) fn allowed_networks(&self) -> impl Iterator<Item = IpNetwork> + '_ {
+        self.allowed_ips.iter().map(|(network, _)| network)
+    }
+
+    /// Additionally allow `network` as a packet source, overriding the predefined bogon list for it.
+    pub(crate) fn allow_source(&mut self, network: impl Into<IpNetwork>) {
+        self.ip_filter.allow(network.into());
+    }
+
+    /// Additionally block `network` as a packet source, even if it isn't in the predefined bogon list.
+    pub(crate) fn block_source(&mut self, network: impl Into<IpNetwork>) {
+        self.ip_filter.block(network.into());
+    }
+
     /// For a given destination IP, return the endpoint to which the DNS query should be sent.
     pub(crate) fn tun_dns_server_endpoint(&self, dst: IpAddr) -> SocketAddr {
         let new_dst_ip = match dst {
@@ -68,19 +87,35 @@ impl GatewayOnClient {
             id,
             allowed_ips: IpNetworkTable::new(),
             gateway_tun,
+            ip_filter: IpFilter::new(),
         }
     }
 }
 
 impl GatewayOnClient {
     pub(crate) fn ensure_allowed_src(&self, packet: &IpPacket) -> anyhow::Result<()> {
-        let src = packet.source();
+        self.ensure_allowed_src_ip(packet.source())
+    }
+
+    fn ensure_allowed_src_ip(&self, raw_src: IpAddr) -> anyhow::Result<()> {
+        let src = canonical(raw_src);
 
-        if self.gateway_tun.is_ip(src) {
+        if self.gateway_tun.is_ip(raw_src) {
             return Ok(());
         }
 
+        // `raw_src` catches an IPv4-mapped form (`::ffff:a.b.c.d`) directly: the whole `/96` is
+        // martian regardless of whether the embedded IPv4 address is itself a bogon, so this must
+        // run against the address as it arrived, before `canonical` decomposes it for `src`.
+        if self.ip_filter.is_blocked(raw_src) || self.ip_filter.is_blocked(src) {
+            tracing::debug!(src = %RedactedIp::from(src), "Rejecting packet with martian source address");
+
+            return Err(anyhow::Error::new(MartianSource(src)));
+        }
+
         if self.allowed_ips.longest_match(src).is_none() {
+            tracing::debug!(src = %RedactedIp::from(src), "Rejecting packet from address with no allowed resource");
+
             return Err(anyhow::Error::new(NotAllowedResource(src)));
         }
 
@@ -91,3 +126,219 @@ impl GatewayOnClient {
         self.id
     }
 }
+
+/// Rejects packets whose source is a bogon / special-use address, in addition to the normal
+/// `allowed_ips` check.
+///
+/// The predefined block set mirrors the well-known reserved IPv4/IPv6 ranges; the control plane
+/// can layer admin-supplied allow/block entries on top of it, with an explicit block always
+/// winning over an explicit allow for the same address.
+struct IpFilter {
+    custom_allow: IpNetworkTable<()>,
+    custom_block: IpNetworkTable<()>,
+}
+
+impl IpFilter {
+    fn new() -> Self {
+        Self {
+            custom_allow: IpNetworkTable::new(),
+            custom_block: IpNetworkTable::new(),
+        }
+    }
+
+    fn allow(&mut self, network: IpNetwork) {
+        self.custom_allow.insert(network, ());
+    }
+
+    fn block(&mut self, network: IpNetwork) {
+        self.custom_block.insert(network, ());
+    }
+
+    fn is_blocked(&self, ip: IpAddr) -> bool {
+        if self.custom_block.longest_match(ip).is_some() {
+            return true;
+        }
+
+        if self.custom_allow.longest_match(ip).is_some() {
+            return false;
+        }
+
+        PREDEFINED_BOGONS.longest_match(ip).is_some()
+    }
+}
+
+/// Reserved / special-use ranges that must never appear as a packet's source address.
+///
+/// [`ensure_allowed_src_ip`](GatewayOnClient::ensure_allowed_src_ip) checks both the raw source and
+/// its [`canonical`] form against this table: the `::ffff:0:0/96` entry below only ever matches the
+/// raw, pre-canonicalization address, since `canonical` decomposes it to its embedded IPv4 form
+/// before the table is checked a second time. This keeps the whole mapped range martian even when
+/// the embedded IPv4 address isn't itself a bogon.
+static PREDEFINED_BOGONS: LazyLock<IpNetworkTable<()>> = LazyLock::new(|| {
+    let mut table = IpNetworkTable::new();
+
+    for network in [
+        "0.0.0.0/8",
+        "127.0.0.0/8",
+        "169.254.0.0/16",
+        "192.0.0.0/24",
+        "192.0.2.0/24",
+        "198.18.0.0/15",
+        "198.51.100.0/24",
+        "203.0.113.0/24",
+        "224.0.0.0/4",
+        "240.0.0.0/4",
+        "255.255.255.255/32",
+        "::/128",
+        "::1/128",
+        "::ffff:0:0/96",
+        "2001:db8::/32",
+        "fe80::/10",
+        "ff00::/8",
+    ] {
+        let network: IpNetwork = network.parse().expect("predefined bogon range is valid");
+        table.insert(network, ());
+    }
+
+    table
+});
+
+/// The packet's source address is a bogon / special-use address that must never be forwarded.
+#[derive(Debug)]
+pub(crate) struct MartianSource(IpAddr);
+
+impl fmt::Display for MartianSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "source address {} is a martian address",
+            RedactedIp::from(self.0)
+        )
+    }
+}
+
+impl std::error::Error for MartianSource {}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use ip_network::Ipv4Network;
+
+    use super::*;
+
+    fn gateway() -> GatewayOnClient {
+        GatewayOnClient::new(
+            GatewayId::from_u128(1),
+            IpConfig {
+                v4: Ipv4Addr::new(100, 64, 0, 1),
+                v6: "fd00:2021:1111::1".parse().unwrap(),
+            },
+        )
+    }
+
+    #[test]
+    fn bogon_source_is_rejected_by_default() {
+        let filter = IpFilter::new();
+
+        assert!(filter.is_blocked(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))));
+    }
+
+    #[test]
+    fn non_bogon_source_is_not_blocked_by_default() {
+        let filter = IpFilter::new();
+
+        assert!(!filter.is_blocked(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))));
+    }
+
+    #[test]
+    fn allow_source_lets_a_bogon_range_through() {
+        let mut filter = IpFilter::new();
+        filter.allow(IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(127, 0, 0, 0), 8).unwrap()));
+
+        assert!(!filter.is_blocked(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))));
+    }
+
+    #[test]
+    fn block_source_wins_over_allow_source_for_the_same_address() {
+        let mut filter = IpFilter::new();
+        let network = IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(1, 1, 1, 0), 24).unwrap());
+
+        filter.allow(network);
+        filter.block(network);
+
+        assert!(filter.is_blocked(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))));
+    }
+
+    #[test]
+    fn ensure_allowed_src_rejects_bogon_with_martian_source_error() {
+        let gateway = gateway();
+
+        let err = gateway
+            .ensure_allowed_src_ip(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)))
+            .unwrap_err();
+
+        assert!(err.downcast_ref::<MartianSource>().is_some());
+    }
+
+    #[test]
+    fn ensure_allowed_src_rejects_non_bogon_with_not_allowed_resource_error() {
+        let gateway = gateway();
+
+        let err = gateway
+            .ensure_allowed_src_ip(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)))
+            .unwrap_err();
+
+        assert!(err.downcast_ref::<NotAllowedResource>().is_some());
+        assert!(err.downcast_ref::<MartianSource>().is_none());
+    }
+
+    #[test]
+    fn ensure_allowed_src_allows_address_covered_by_allow_source() {
+        let mut gateway = gateway();
+        gateway.allow_source(IpNetwork::V4(
+            Ipv4Network::new(Ipv4Addr::new(127, 0, 0, 0), 8).unwrap(),
+        ));
+        gateway.allow_ip_for_resource(
+            IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(127, 0, 0, 1), 32).unwrap()),
+            ResourceId::from_u128(1),
+        );
+
+        assert!(
+            gateway
+                .ensure_allowed_src_ip(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn ensure_allowed_src_rejects_non_bogon_ipv4_mapped_source() {
+        let gateway = gateway();
+
+        // `8.8.8.8` alone isn't a bogon, but arriving as a raw `::ffff:8.8.8.8` source is itself
+        // martian: real packets never carry an IPv4-mapped address as their wire source.
+        let err = gateway
+            .ensure_allowed_src_ip("::ffff:8.8.8.8".parse().unwrap())
+            .unwrap_err();
+
+        assert!(err.downcast_ref::<MartianSource>().is_some());
+    }
+
+    #[test]
+    fn ensure_allowed_src_rejects_address_covered_by_block_source_even_if_allowed() {
+        let mut gateway = gateway();
+        gateway.allow_ip_for_resource(
+            IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(1, 1, 1, 1), 32).unwrap()),
+            ResourceId::from_u128(1),
+        );
+        gateway.block_source(IpNetwork::V4(
+            Ipv4Network::new(Ipv4Addr::new(1, 1, 1, 0), 24).unwrap(),
+        ));
+
+        let err = gateway
+            .ensure_allowed_src_ip(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)))
+            .unwrap_err();
+
+        assert!(err.downcast_ref::<MartianSource>().is_some());
+    }
+}
@@ -1,20 +1,27 @@
 use core::fmt;
 use std::collections::HashMap;
-use std::hash::Hash;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use ip_network::{IpNetwork, Ipv4Network, Ipv6Network};
+use ip_network_table::IpNetworkTable;
 
 use crate::client::GatewayOnClient;
 use crate::gateway::ClientOnGateway;
 
 pub(crate) struct PeerStore<TId, P> {
-    id_by_ip: HashMap<IpAddr, TId>,
+    id_by_ip: IpNetworkTable<TId>,
     peer_by_id: HashMap<TId, P>,
 }
 
 impl<TId, P> Default for PeerStore<TId, P> {
     fn default() -> Self {
         Self {
-            id_by_ip: Default::default(),
+            id_by_ip: IpNetworkTable::new(),
             peer_by_id: Default::default(),
         }
     }
@@ -39,28 +46,33 @@ where
 
     pub(crate) fn upsert(&mut self, pid: TId, make_peer: impl FnOnce() -> P) -> &mut P {
         let peer = make_peer();
-
-        if let Some(existing) = self.peer_by_id.get(&pid)
-            && (existing.tun_ipv4() != peer.tun_ipv4() || existing.tun_ipv6() != peer.tun_ipv6())
-        {
-            tracing::debug!(
-                %pid,
-                old_v4 = %existing.tun_ipv4(),
-                old_v6 = %existing.tun_ipv6(),
-                new_v4 = %peer.tun_ipv4(),
-                new_v6 = %peer.tun_ipv6(),
-                "Peer's TUN IP has changed, replacing",
-            );
-
-            self.id_by_ip.remove(&existing.tun_ipv4().into());
-            self.id_by_ip.remove(&existing.tun_ipv6().into());
-            self.peer_by_id.remove(&pid);
+        let new_networks = peer_networks(&peer).collect::<Vec<_>>();
+
+        if let Some(existing) = self.peer_by_id.get(&pid) {
+            let old_networks = peer_networks(existing).collect::<Vec<_>>();
+
+            if old_networks != new_networks {
+                tracing::debug!(
+                    %pid,
+                    old_v4 = %RedactedIp::from(existing.tun_ipv4()),
+                    old_v6 = %RedactedIp::from(existing.tun_ipv6()),
+                    new_v4 = %RedactedIp::from(peer.tun_ipv4()),
+                    new_v6 = %RedactedIp::from(peer.tun_ipv6()),
+                    old_networks = old_networks.len(),
+                    new_networks = new_networks.len(),
+                    "Peer's routing information has changed, replacing",
+                );
+
+                self.id_by_ip.retain(|_, r_id| r_id != &pid);
+                self.peer_by_id.remove(&pid);
+            }
         }
 
         let peer = self.peer_by_id.entry(pid).or_insert(peer);
 
-        self.id_by_ip.insert(peer.tun_ipv4().into(), pid);
-        self.id_by_ip.insert(peer.tun_ipv6().into(), pid);
+        for network in new_networks {
+            self.id_by_ip.insert(network, pid);
+        }
 
         peer
     }
@@ -70,6 +82,26 @@ where
         self.peer_by_id.remove(id)
     }
 
+    /// Rebuilds `id_by_ip`'s entries for `id` from its peer's current TUN addresses and
+    /// `owned_networks()`.
+    ///
+    /// `upsert` only reindexes a peer it is itself replacing, so call this after mutating a
+    /// stored peer's owned networks directly (e.g. through `peer_by_id_mut`) — such as
+    /// authorizing or revoking a resource CIDR on an already-connected gateway — to keep
+    /// `peer_by_ip` in sync.
+    pub(crate) fn reindex(&mut self, id: &TId) {
+        let networks = match self.peer_by_id.get(id) {
+            Some(peer) => peer_networks(peer).collect::<Vec<_>>(),
+            None => return,
+        };
+
+        self.id_by_ip.retain(|_, r_id| r_id != id);
+
+        for network in networks {
+            self.id_by_ip.insert(network, *id);
+        }
+    }
+
     pub(crate) fn peer_by_id(&self, id: &TId) -> Option<&P> {
         self.peer_by_id.get(id)
     }
@@ -78,15 +110,16 @@ where
         self.peer_by_id.get_mut(id)
     }
 
+    /// Looks up the peer responsible for `ip`, picking the most specific (longest-prefix) match.
     pub(crate) fn peer_by_ip(&self, ip: IpAddr) -> Option<&P> {
-        let id = self.id_by_ip.get(&ip)?;
+        let (_, id) = self.id_by_ip.longest_match(canonical(ip))?;
         let peer = self.peer_by_id.get(id)?;
 
         Some(peer)
     }
 
     pub(crate) fn peer_by_ip_mut(&mut self, ip: IpAddr) -> Option<&mut P> {
-        let id = self.id_by_ip.get(&ip)?;
+        let (_, id) = self.id_by_ip.longest_match(canonical(ip))?;
         let peer = self.peer_by_id.get_mut(id)?;
 
         Some(peer)
@@ -97,14 +130,150 @@ where
     }
 
     pub(crate) fn clear(&mut self) {
-        self.id_by_ip.clear();
+        self.id_by_ip = IpNetworkTable::new();
         self.peer_by_id.clear();
     }
 }
 
+/// Collapses an IPv4-mapped (`::ffff:a.b.c.d`) or IPv4-compatible (`::a.b.c.d`) IPv6 address to
+/// its canonical IPv4 form, so both representations of the same address hash to the same key.
+pub(crate) fn canonical(ip: IpAddr) -> IpAddr {
+    let IpAddr::V6(v6) = ip else {
+        return ip;
+    };
+
+    if let Some(v4) = v6.to_ipv4_mapped() {
+        return IpAddr::V4(v4);
+    }
+
+    if let Some(v4) = v6.to_ipv4() {
+        if v6 != Ipv6Addr::UNSPECIFIED && v6 != Ipv6Addr::LOCALHOST {
+            return IpAddr::V4(v4);
+        }
+    }
+
+    ip
+}
+
+/// The peer's TUN addresses as their degenerate `/32`-`/128` networks.
+fn tun_networks(ipv4: Ipv4Addr, ipv6: Ipv6Addr) -> impl Iterator<Item = IpNetwork> {
+    iter::once(IpNetwork::V4(
+        Ipv4Network::new(ipv4, 32).expect("/32 is always a valid IPv4 prefix length"),
+    ))
+    .chain(iter::once(IpNetwork::V6(
+        Ipv6Network::new(ipv6, 128).expect("/128 is always a valid IPv6 prefix length"),
+    )))
+}
+
+/// All networks `peer` should be reachable at: its TUN addresses plus anything it owns.
+fn peer_networks<P: Peer + ?Sized>(peer: &P) -> impl Iterator<Item = IpNetwork> + '_ {
+    tun_networks(peer.tun_ipv4(), peer.tun_ipv6()).chain(peer.owned_networks())
+}
+
+/// Whether [`RedactedIp`]/[`RedactedAddr`] print the real address instead of a redacted token.
+///
+/// Defaults to `false` so logs are redacted unless a deployment explicitly opts back into full
+/// addresses (e.g. via a runtime setting wired up by the caller) for local debugging.
+static REVEAL_REAL_IPS: AtomicBool = AtomicBool::new(false);
+
+/// Restores full, un-redacted addresses in [`RedactedIp`]/[`RedactedAddr`] tracing output.
+///
+/// Intended to be called once at startup from a runtime setting; compliance-sensitive
+/// deployments should leave this unset so logs stay redacted by default.
+pub(crate) fn set_reveal_real_ips(reveal: bool) {
+    REVEAL_REAL_IPS.store(reveal, Ordering::Relaxed);
+}
+
+/// Salts the token in [`RedactedIp`]/[`RedactedAddr`], chosen once per process so the same
+/// address always redacts to the same token within a run but can't be reversed across runs.
+static REDACTION_SALT: LazyLock<RandomState> = LazyLock::new(RandomState::new);
+
+fn redacted_token(ip: IpAddr) -> u64 {
+    let mut hasher = REDACTION_SALT.build_hasher();
+    ip.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// An [`IpAddr`] that displays as an irreversible, per-process token instead of its real value.
+///
+/// Only formatting changes: the wrapped address is not exposed outside this module, so callers
+/// wrap an address they keep using themselves. See [`set_reveal_real_ips`] to opt back into full
+/// addresses.
+pub(crate) struct RedactedIp(IpAddr);
+
+impl From<IpAddr> for RedactedIp {
+    fn from(ip: IpAddr) -> Self {
+        Self(ip)
+    }
+}
+
+impl From<Ipv4Addr> for RedactedIp {
+    fn from(ip: Ipv4Addr) -> Self {
+        Self(ip.into())
+    }
+}
+
+impl From<Ipv6Addr> for RedactedIp {
+    fn from(ip: Ipv6Addr) -> Self {
+        Self(ip.into())
+    }
+}
+
+impl fmt::Display for RedactedIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if REVEAL_REAL_IPS.load(Ordering::Relaxed) {
+            return write!(f, "{}", self.0);
+        }
+
+        let family = match self.0 {
+            IpAddr::V4(_) => "v4",
+            IpAddr::V6(_) => "v6",
+        };
+
+        write!(f, "redacted:{family}:{:016x}", redacted_token(self.0))
+    }
+}
+
+impl fmt::Debug for RedactedIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// A [`SocketAddr`] whose IP redacts like [`RedactedIp`] while keeping the port visible, since
+/// the port alone is rarely sensitive but is often needed to debug a connection.
+pub(crate) struct RedactedAddr(SocketAddr);
+
+impl From<SocketAddr> for RedactedAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self(addr)
+    }
+}
+
+impl fmt::Display for RedactedAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", RedactedIp(self.0.ip()), self.0.port())
+    }
+}
+
+impl fmt::Debug for RedactedAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
 pub(crate) trait Peer {
     fn tun_ipv4(&self) -> Ipv4Addr;
     fn tun_ipv6(&self) -> Ipv6Addr;
+
+    /// IP networks owned by this peer, beyond its TUN addresses.
+    ///
+    /// This lets a peer (e.g. a gateway fronting a subnet) claim an entire CIDR range so that
+    /// `PeerStore::peer_by_ip` can route packets destined for any IP within it, not just the
+    /// peer's own host addresses.
+    fn owned_networks(&self) -> impl Iterator<Item = IpNetwork> {
+        iter::empty()
+    }
 }
 
 impl Peer for ClientOnGateway {
@@ -125,11 +294,18 @@ impl Peer for GatewayOnClient {
     fn tun_ipv6(&self) -> Ipv6Addr {
         self.gateway_tun().v6
     }
+
+    fn owned_networks(&self) -> impl Iterator<Item = IpNetwork> {
+        self.allowed_networks()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use connlib_model::{GatewayId, ResourceId};
+
     use super::*;
+    use crate::IpConfig;
 
     struct DummyPeer {
         id: u64,
@@ -153,6 +329,27 @@ mod tests {
         }
     }
 
+    struct SubnetPeer {
+        id: u64,
+        ipv4: Ipv4Addr,
+        ipv6: Ipv6Addr,
+        network: IpNetwork,
+    }
+
+    impl Peer for SubnetPeer {
+        fn tun_ipv4(&self) -> Ipv4Addr {
+            self.ipv4
+        }
+
+        fn tun_ipv6(&self) -> Ipv6Addr {
+            self.ipv6
+        }
+
+        fn owned_networks(&self) -> impl Iterator<Item = IpNetwork> {
+            iter::once(self.network)
+        }
+    }
+
     #[test]
     fn can_insert_and_retrieve_peer() {
         let mut peer_storage = PeerStore::<u64, DummyPeer>::default();
@@ -211,4 +408,249 @@ mod tests {
                 .is_none()
         )
     }
+
+    #[test]
+    fn peer_owning_a_network_matches_any_ip_inside_it() {
+        let mut peer_storage = PeerStore::<u64, SubnetPeer>::default();
+        peer_storage.upsert(0, || SubnetPeer {
+            id: 0,
+            ipv4: Ipv4Addr::new(10, 0, 0, 1),
+            ipv6: Ipv6Addr::LOCALHOST,
+            network: IpNetwork::V4(
+                Ipv4Network::new(Ipv4Addr::new(10, 1, 0, 0), 16).unwrap(),
+            ),
+        });
+
+        assert_eq!(
+            peer_storage
+                .peer_by_ip("10.1.2.3".parse().unwrap())
+                .unwrap()
+                .id,
+            0
+        );
+    }
+
+    #[test]
+    fn longest_match_prefers_more_specific_peer() {
+        let mut peer_storage = PeerStore::<u64, SubnetPeer>::default();
+        peer_storage.upsert(0, || SubnetPeer {
+            id: 0,
+            ipv4: Ipv4Addr::new(10, 0, 0, 1),
+            ipv6: Ipv6Addr::LOCALHOST,
+            network: IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(10, 0, 0, 0), 8).unwrap()),
+        });
+        peer_storage.upsert(1, || SubnetPeer {
+            id: 1,
+            ipv4: Ipv4Addr::new(10, 1, 0, 1),
+            ipv6: Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 2),
+            network: IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(10, 1, 0, 0), 16).unwrap()),
+        });
+
+        assert_eq!(
+            peer_storage
+                .peer_by_ip("10.1.2.3".parse().unwrap())
+                .unwrap()
+                .id,
+            1
+        );
+    }
+
+    #[test]
+    fn upsert_reindexes_when_only_owned_networks_change() {
+        let mut peer_storage = PeerStore::<u64, SubnetPeer>::default();
+        peer_storage.upsert(0, || SubnetPeer {
+            id: 0,
+            ipv4: Ipv4Addr::new(10, 0, 0, 1),
+            ipv6: Ipv6Addr::LOCALHOST,
+            network: IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(10, 0, 0, 0), 8).unwrap()),
+        });
+
+        assert!(
+            peer_storage
+                .peer_by_ip("10.5.5.5".parse().unwrap())
+                .is_some()
+        );
+
+        peer_storage.upsert(0, || SubnetPeer {
+            id: 0,
+            ipv4: Ipv4Addr::new(10, 0, 0, 1),
+            ipv6: Ipv6Addr::LOCALHOST,
+            network: IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(192, 168, 0, 0), 16).unwrap()),
+        });
+
+        assert!(
+            peer_storage
+                .peer_by_ip("10.5.5.5".parse().unwrap())
+                .is_none()
+        );
+        assert_eq!(
+            peer_storage
+                .peer_by_ip("192.168.1.1".parse().unwrap())
+                .unwrap()
+                .id,
+            0
+        );
+    }
+
+    #[test]
+    fn gateway_on_client_resource_cidrs_are_used_for_routing() {
+        let mut peer_storage = PeerStore::<GatewayId, GatewayOnClient>::default();
+        let id = GatewayId::from_u128(1);
+
+        peer_storage.upsert(id, || {
+            let mut gateway = GatewayOnClient::new(
+                id,
+                IpConfig {
+                    v4: Ipv4Addr::new(100, 64, 0, 1),
+                    v6: "fd00:2021:1111::1".parse().unwrap(),
+                },
+            );
+            gateway.allow_ip_for_resource(
+                IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(10, 1, 0, 0), 16).unwrap()),
+                ResourceId::from_u128(1),
+            );
+
+            gateway
+        });
+
+        assert_eq!(
+            peer_storage
+                .peer_by_ip("10.1.2.3".parse().unwrap())
+                .unwrap()
+                .id(),
+            id
+        );
+    }
+
+    #[test]
+    fn reindex_applies_resource_changes_made_directly_on_a_stored_peer() {
+        let mut peer_storage = PeerStore::<GatewayId, GatewayOnClient>::default();
+        let id = GatewayId::from_u128(1);
+
+        peer_storage.upsert(id, || {
+            GatewayOnClient::new(
+                id,
+                IpConfig {
+                    v4: Ipv4Addr::new(100, 64, 0, 1),
+                    v6: "fd00:2021:1111::1".parse().unwrap(),
+                },
+            )
+        });
+
+        assert!(
+            peer_storage
+                .peer_by_ip("10.1.2.3".parse().unwrap())
+                .is_none()
+        );
+
+        let resource = ResourceId::from_u128(1);
+        let network = IpNetwork::V4(Ipv4Network::new(Ipv4Addr::new(10, 1, 0, 0), 16).unwrap());
+
+        // A resource authorized directly on the already-stored peer (the realistic path: a
+        // gateway stays connected while resources are authorized/revoked) must not route until
+        // `reindex` is called.
+        peer_storage
+            .peer_by_id_mut(&id)
+            .unwrap()
+            .allow_ip_for_resource(network, resource);
+
+        assert!(
+            peer_storage
+                .peer_by_ip("10.1.2.3".parse().unwrap())
+                .is_none()
+        );
+
+        peer_storage.reindex(&id);
+
+        assert_eq!(
+            peer_storage
+                .peer_by_ip("10.1.2.3".parse().unwrap())
+                .unwrap()
+                .id(),
+            id
+        );
+
+        peer_storage
+            .peer_by_id_mut(&id)
+            .unwrap()
+            .remove_resource(resource);
+        peer_storage.reindex(&id);
+
+        assert!(
+            peer_storage
+                .peer_by_ip("10.1.2.3".parse().unwrap())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn ipv4_mapped_ipv6_address_resolves_to_same_peer_as_plain_ipv4() {
+        let mut peer_storage = PeerStore::<u64, DummyPeer>::default();
+        peer_storage.upsert(0, || {
+            DummyPeer::new(0, Ipv4Addr::new(10, 0, 0, 1), Ipv6Addr::LOCALHOST)
+        });
+
+        let by_v4 = peer_storage.peer_by_ip("10.0.0.1".parse().unwrap());
+        let by_mapped_v6 = peer_storage.peer_by_ip("::ffff:10.0.0.1".parse().unwrap());
+
+        assert_eq!(by_v4.unwrap().id, 0);
+        assert_eq!(by_mapped_v6.unwrap().id, 0);
+    }
+
+    #[test]
+    fn canonical_collapses_ipv4_mapped_and_compatible_forms() {
+        assert_eq!(
+            canonical("::ffff:10.0.0.1".parse().unwrap()),
+            "10.0.0.1".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            canonical("::10.0.0.1".parse().unwrap()),
+            "10.0.0.1".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            canonical("::1".parse().unwrap()),
+            "::1".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            canonical("10.0.0.1".parse().unwrap()),
+            "10.0.0.1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    /// Serializes the tests below so they don't race on the process-global `REVEAL_REAL_IPS` flag.
+    static REVEAL_REAL_IPS_TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[test]
+    fn redacted_ip_hides_the_real_address_by_default() {
+        let _guard = REVEAL_REAL_IPS_TEST_LOCK.lock().unwrap();
+        set_reveal_real_ips(false);
+
+        let redacted = RedactedIp::from(Ipv4Addr::new(10, 0, 0, 1)).to_string();
+
+        assert!(!redacted.contains("10.0.0.1"));
+    }
+
+    #[test]
+    fn set_reveal_real_ips_restores_the_real_address() {
+        let _guard = REVEAL_REAL_IPS_TEST_LOCK.lock().unwrap();
+        set_reveal_real_ips(true);
+
+        let revealed = RedactedIp::from(Ipv4Addr::new(10, 0, 0, 1)).to_string();
+
+        set_reveal_real_ips(false);
+
+        assert_eq!(revealed, "10.0.0.1");
+    }
+
+    #[test]
+    fn redacted_addr_keeps_the_port_but_redacts_the_ip() {
+        let _guard = REVEAL_REAL_IPS_TEST_LOCK.lock().unwrap();
+        set_reveal_real_ips(false);
+
+        let addr: SocketAddr = "10.0.0.1:4242".parse().unwrap();
+        let redacted = RedactedAddr::from(addr).to_string();
+
+        assert!(!redacted.contains("10.0.0.1"));
+        assert!(redacted.ends_with(":4242"));
+    }
 }